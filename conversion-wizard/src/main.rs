@@ -1,14 +1,20 @@
+mod npz;
 mod util;
 
 use std::collections::HashMap;
 use std::fs::File;
 use std::path::PathBuf;
+// `write_at` lets multiple worker threads write to disjoint regions of the same `File`
+// concurrently without a shared cursor or lock, which is how `do_quantize` streams its
+// parallel output; this tool otherwise already assumes a Unix-like host (e.g. `ggml_sys`).
+use std::os::unix::fs::FileExt;
 use std::{borrow::Cow, io::Write};
 
 use anyhow::{bail, ensure, Context};
 use clap::Parser;
 use memmap2::MmapOptions;
-use safetensors::{Dtype, SafeTensors};
+use rayon::prelude::*;
+use safetensors::{Dtype, SafeTensors, View};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
@@ -17,8 +23,12 @@ use serde_json::json;
 #[command(author, version, about, long_about = None)]
 enum Args {
     ConvertPyTorch(ConvertPyTorchArgs),
+    ConvertNpz(ConvertNpzArgs),
+    ExportNpz(ExportNpzArgs),
     Plan(PlanArgs),
     Quantize(QuantizeArgs),
+    Dequantize(DequantizeArgs),
+    Verify(VerifyArgs),
 }
 
 #[derive(Parser, Debug)]
@@ -32,6 +42,28 @@ struct ConvertPyTorchArgs {
     model_out: PathBuf,
 }
 
+#[derive(Parser, Debug)]
+struct ConvertNpzArgs {
+    /// input NumPy (.npz) archive
+    #[arg()]
+    model_in: PathBuf,
+
+    /// output .safetensors file
+    #[arg()]
+    model_out: PathBuf,
+}
+
+#[derive(Parser, Debug)]
+struct ExportNpzArgs {
+    /// input .safetensors file
+    #[arg()]
+    model_in: PathBuf,
+
+    /// output NumPy (.npz) archive
+    #[arg()]
+    model_out: PathBuf,
+}
+
 #[derive(Parser, Debug)]
 struct PlanArgs {
     /// input .safetensors file
@@ -58,13 +90,39 @@ struct QuantizeArgs {
     model_out: PathBuf,
 }
 
+#[derive(Parser, Debug)]
+struct DequantizeArgs {
+    /// quantized .safetensors file
+    #[arg()]
+    model_in: PathBuf,
+
+    /// output plain F32 .safetensors file name
+    #[arg()]
+    model_out: PathBuf,
+}
+
+#[derive(Parser, Debug)]
+struct VerifyArgs {
+    /// original, unquantized .safetensors file
+    #[arg()]
+    model_orig: PathBuf,
+
+    /// quantized .safetensors file to check
+    #[arg()]
+    model_quantized: PathBuf,
+}
+
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
     match args {
         Args::ConvertPyTorch(args) => do_the_pickle_thing(args),
+        Args::ConvertNpz(args) => do_convert_npz(args),
+        Args::ExportNpz(args) => do_export_npz(args),
         Args::Plan(args) => do_plan(args),
         Args::Quantize(args) => do_quantize(args),
+        Args::Dequantize(args) => do_dequantize(args),
+        Args::Verify(args) => do_verify(args),
     }
 }
 
@@ -134,10 +192,127 @@ fn do_the_pickle_thing(args: ConvertPyTorchArgs) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+struct RawNpyTensor {
+    name: String,
+    dtype: Dtype,
+    shape: Vec<usize>,
+    /// the whole decompressed `.npy` member; `data_offset` marks where the array data begins
+    buffer: Vec<u8>,
+    data_offset: usize,
+}
+
+/// load a NumPy `.npz` archive (a ZIP of `.npy` arrays)
+fn do_convert_npz(args: ConvertNpzArgs) -> anyhow::Result<()> {
+    let file = File::open(&args.model_in)?;
+    let mut archive = zip::ZipArchive::new(file)?;
+
+    let mut raw_tensors = vec![];
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let Some(name) = entry.name().strip_suffix(".npy") else {
+            continue;
+        };
+        let name = name.to_owned();
+
+        let mut buffer = Vec::with_capacity(entry.size() as usize);
+        std::io::Read::read_to_end(&mut entry, &mut buffer)?;
+
+        let (header, data) = npz::parse_npy(&buffer)?;
+        ensure!(
+            !header.fortran_order,
+            "array {name:?} is fortran-ordered, transpose it before converting"
+        );
+        let dtype = npz::npy_dtype_to_safetensors(&header.descr)?;
+        let data_offset = buffer.len() - data.len();
+
+        raw_tensors.push(RawNpyTensor {
+            name,
+            dtype,
+            shape: header.shape,
+            buffer,
+            data_offset,
+        });
+    }
+
+    let tensor_slices: Vec<(String, TensorView)> = raw_tensors
+        .iter()
+        .map(|t| {
+            (
+                t.name.clone(),
+                TensorView {
+                    dtype: t.dtype,
+                    shape: t.shape.clone(),
+                    data: &t.buffer[t.data_offset..],
+                },
+            )
+        })
+        .collect();
+
+    safetensors::serialize_to_file(tensor_slices, &None, &args.model_out)?;
+    Ok(())
+}
+
+/// export a `.safetensors` file (plain, or `i8_affine`/`u8_affine`-quantized) to a NumPy `.npz`
+/// archive for inspection; files holding ggml-block-quantized tensors (`"q4_0"`, ...) are
+/// rejected, since `SafeTensors::deserialize` does not accept their dtype strings
+fn do_export_npz(args: ExportNpzArgs) -> anyhow::Result<()> {
+    let file = File::open(&args.model_in)?;
+    let buffer = unsafe { MmapOptions::new().map(&file)? };
+    let tensors = SafeTensors::deserialize(&buffer)?;
+
+    let out_file = File::create(&args.model_out)?;
+    let mut zip = zip::ZipWriter::new(out_file);
+    let options = zip::write::FileOptions::default();
+
+    for (name, tensor) in tensors.tensors() {
+        let descr = npz::safetensors_dtype_to_npy(tensor.dtype())?;
+        let header = npz::build_npy_header(descr, tensor.shape());
+        zip.start_file(format!("{name}.npy"), options)?;
+        zip.write_all(&header)?;
+        zip.write_all(&tensor.data())?;
+    }
+
+    zip.finish()?;
+    Ok(())
+}
+
 #[derive(Serialize, Deserialize)]
 struct QuantizePlan {
     metadata: Option<HashMap<String, String>>,
     catalog: HashMap<QuantizeKey, QuantizeInfo>,
+    /// per-tensor-name overrides, checked before `catalog`; `String` is a glob pattern
+    /// (`*` matches any run of characters) such as `*.attn.*.weight`. First match wins.
+    #[serde(default)]
+    overrides: Vec<(String, QuantizeTreatment)>,
+}
+
+/// Match `text` against a glob `pattern` where `*` matches any run of characters.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == text;
+    }
+
+    let mut pos = 0;
+    for (i, part) in parts.iter().enumerate() {
+        if part.is_empty() {
+            continue;
+        }
+        if i == 0 {
+            let Some(rest) = text[pos..].strip_prefix(part) else {
+                return false;
+            };
+            pos = text.len() - rest.len();
+        } else if i == parts.len() - 1 {
+            return text[pos..].ends_with(part);
+        } else {
+            match text[pos..].find(part) {
+                Some(idx) => pos += idx + part.len(),
+                None => return false,
+            }
+        }
+    }
+    true
 }
 
 #[derive(Hash, PartialEq, Eq, Serialize, Deserialize, Debug, Clone)]
@@ -154,7 +329,7 @@ struct QuantizeInfo {
 }
 
 #[allow(non_camel_case_types)]
-#[derive(Default, Serialize, Deserialize)]
+#[derive(Default, Serialize, Deserialize, Debug, Clone, Copy)]
 enum QuantizeTreatment {
     #[default]
     keep,
@@ -164,6 +339,45 @@ enum QuantizeTreatment {
     q5_0,
     q5_1,
     q8_0,
+    /// symmetric affine int8: `scale = max(|x|)/127`, `q = round_ties_to_even(x/scale)`
+    i8_affine,
+    /// asymmetric affine uint8: `scale = (max-min)/255`, `zero_point = round_ties_to_even(-min/scale)`
+    u8_affine,
+}
+
+/// Round half to even, as tract's datum conversions do (avoids the statistical bias
+/// of round-half-away-from-zero when quantizing many near-.5 values).
+fn round_ties_to_even(x: f32) -> f32 {
+    let floor = x.floor();
+    match x - floor {
+        diff if diff < 0.5 => floor,
+        diff if diff > 0.5 => floor + 1.0,
+        _ if (floor as i64) % 2 == 0 => floor,
+        _ => floor + 1.0,
+    }
+}
+
+/// `scale = max(|x|)/127` for `i8_affine` (see [`QuantizeTreatment::i8_affine`]).
+fn i8_affine_scale(values: &[f32]) -> f32 {
+    let max_abs = values.iter().fold(0f32, |acc, &x| acc.max(x.abs()));
+    if max_abs == 0.0 {
+        1.0
+    } else {
+        max_abs / 127.0
+    }
+}
+
+/// `scale = (max-min)/255`, `zero_point = round_ties_to_even(-min/scale)` for `u8_affine`
+/// (see [`QuantizeTreatment::u8_affine`]).
+fn u8_affine_params(values: &[f32]) -> (f32, i32) {
+    let (min, max) = values
+        .iter()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &x| {
+            (lo.min(x), hi.max(x))
+        });
+    let scale = if max == min { 1.0 } else { (max - min) / 255.0 };
+    let zero_point = round_ties_to_even(-min / scale) as i32;
+    (scale, zero_point)
 }
 
 fn do_plan(args: PlanArgs) -> Result<(), anyhow::Error> {
@@ -173,7 +387,8 @@ fn do_plan(args: PlanArgs) -> Result<(), anyhow::Error> {
     let metadata = header.metadata().clone();
 
     let mut tensors: HashMap<QuantizeKey, QuantizeInfo> = HashMap::new();
-    for (_tensor_name, tensor_info) in header.tensors() {
+    let mut name_patterns: std::collections::BTreeSet<String> = Default::default();
+    for (tensor_name, tensor_info) in header.tensors() {
         let k = QuantizeKey {
             dtype: util::dtype_str(&tensor_info.dtype).to_owned(),
             shape: tensor_info.shape.clone(),
@@ -187,6 +402,7 @@ fn do_plan(args: PlanArgs) -> Result<(), anyhow::Error> {
         };
 
         info.count += 1;
+        name_patterns.insert(generalize_tensor_name(&tensor_name));
     }
 
     let writer = File::create(args.plan_out)?;
@@ -195,12 +411,159 @@ fn do_plan(args: PlanArgs) -> Result<(), anyhow::Error> {
         &QuantizePlan {
             metadata,
             catalog: tensors,
+            overrides: vec![],
         },
     )?;
 
+    writeln!(
+        &writer,
+        "\n# tensor-name patterns discovered in this model, for editing `overrides:` above:"
+    )?;
+    for pattern in name_patterns {
+        writeln!(&writer, "# - [\"{pattern}\", keep]")?;
+    }
+
     Ok(())
 }
 
+/// Generalize a tensor name into a glob pattern by collapsing runs of digits (e.g. layer
+/// indices) into `*`, so e.g. `model.layers.3.attn.q_proj.weight` and
+/// `model.layers.14.attn.q_proj.weight` both surface as one discovered pattern.
+fn generalize_tensor_name(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut chars = name.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() {
+            out.push('*');
+            while chars.next_if(|c| c.is_ascii_digit()).is_some() {}
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Number of elements per block and bytes per block for ggml's block-quantized formats
+/// (the `QK*`/`block_q*_*` constants from ggml.c), so the planning pass can size a
+/// tensor's quantized output without quantizing it.
+fn ggml_block_layout(treatment: QuantizeTreatment) -> (usize, usize) {
+    match treatment {
+        QuantizeTreatment::q4_0 => (32, 20),
+        QuantizeTreatment::q4_1 => (32, 24),
+        QuantizeTreatment::q5_0 => (32, 22),
+        QuantizeTreatment::q5_1 => (32, 24),
+        QuantizeTreatment::q8_0 => (32, 36),
+        other => unreachable!("{other:?} is not a ggml block format"),
+    }
+}
+
+fn ggml_treatment_name(treatment: QuantizeTreatment) -> &'static str {
+    match treatment {
+        QuantizeTreatment::q4_0 => "q4_0",
+        QuantizeTreatment::q4_1 => "q4_1",
+        QuantizeTreatment::q5_0 => "q5_0",
+        QuantizeTreatment::q5_1 => "q5_1",
+        QuantizeTreatment::q8_0 => "q8_0",
+        other => unreachable!("{other:?} is not a ggml block format"),
+    }
+}
+
+/// A tensor's resolved output shape: what it will be called, where it lives in the
+/// output file, and how it's derived from the input — computed by the planning pass from
+/// shape/treatment alone. The one exception is `i8_affine`/`u8_affine`'s scale/zero_point,
+/// which need one pass over the tensor's values to fix (nothing else is retained).
+struct PlannedTensor {
+    name: String,
+    treatment: QuantizeTreatment,
+    shape: Vec<usize>,
+    /// (scale, zero_point), set only for `i8_affine`/`u8_affine`
+    affine_params: Option<(f32, i32)>,
+    /// (start, end) byte offsets within the data region (after the header)
+    offsets: (usize, usize),
+}
+
+/// Quantize one tensor's data according to its plan, using `scratch` as reusable working
+/// space (sized by the caller to the largest ggml-block-quantized tensor, the only treatments
+/// that use it) instead of allocating per tensor.
+/// Returns the bytes to write and, for ggml-quantized tensors, their loss histogram.
+fn quantize_tensor(
+    plan: &PlannedTensor,
+    dtype: Dtype,
+    data: &[u8],
+    scratch: &mut [u8],
+) -> anyhow::Result<(Vec<u8>, Option<[i64; 16]>)> {
+    if let QuantizeTreatment::keep = plan.treatment {
+        return Ok((data.to_vec(), None));
+    }
+
+    ensure!(dtype == Dtype::F32, "only F32 tensors can be quantized");
+    ensure!(data.len() % dtype.size() == 0);
+    let n_elem = data.len() / dtype.size();
+    let data_f32: &[f32] = unsafe { std::slice::from_raw_parts(data.as_ptr().cast(), n_elem) };
+
+    match plan.treatment {
+        QuantizeTreatment::keep => unreachable!(),
+
+        QuantizeTreatment::i8_affine => {
+            let (scale, _) = plan.affine_params.expect("i8_affine plan always has scale");
+            let converted: Vec<u8> = data_f32
+                .iter()
+                .map(|&x| round_ties_to_even(x / scale).clamp(-127.0, 127.0) as i8 as u8)
+                .collect();
+            Ok((converted, None))
+        }
+        QuantizeTreatment::u8_affine => {
+            let (scale, zero_point) = plan
+                .affine_params
+                .expect("u8_affine plan always has scale/zero_point");
+            let converted: Vec<u8> = data_f32
+                .iter()
+                .map(|&x| {
+                    (round_ties_to_even(x / scale) + zero_point as f32).clamp(0.0, 255.0) as u8
+                })
+                .collect();
+            Ok((converted, None))
+        }
+        QuantizeTreatment::q4_0
+        | QuantizeTreatment::q4_1
+        | QuantizeTreatment::q5_0
+        | QuantizeTreatment::q5_1
+        | QuantizeTreatment::q8_0 => {
+            let mut loss = [0i64; 1 << 4];
+            let ne_0 = *plan
+                .shape
+                .iter()
+                .find(|&&x| x != 1)
+                .with_context(|| "tensor has no dim that != 1")?;
+
+            macro_rules! quantize {
+                ($func:ident) => {{
+                    let cur_size = unsafe {
+                        ggml_sys::$func(
+                            std::mem::transmute(data.as_ptr()),
+                            std::mem::transmute(scratch.as_mut_ptr()),
+                            n_elem.try_into()?,
+                            ne_0.try_into()?,
+                            loss.as_mut_ptr(),
+                        )
+                    };
+                    scratch[0..cur_size].to_vec()
+                }};
+            }
+
+            let converted = match plan.treatment {
+                QuantizeTreatment::q4_0 => quantize!(ggml_quantize_q4_0),
+                QuantizeTreatment::q4_1 => quantize!(ggml_quantize_q4_1),
+                QuantizeTreatment::q8_0 => quantize!(ggml_quantize_q8_0),
+                QuantizeTreatment::q5_0 => quantize!(ggml_quantize_q5_0),
+                QuantizeTreatment::q5_1 => quantize!(ggml_quantize_q5_1),
+                _ => unreachable!(),
+            };
+            Ok((converted, Some(loss)))
+        }
+    }
+}
+
 fn do_quantize(args: QuantizeArgs) -> anyhow::Result<()> {
     let plan_file = File::open(args.plan)?;
     let plan: QuantizePlan = serde_yaml::from_reader(plan_file)?;
@@ -208,142 +571,456 @@ fn do_quantize(args: QuantizeArgs) -> anyhow::Result<()> {
     let buffer = unsafe { MmapOptions::new().map(&file)? };
     let tensors = SafeTensors::deserialize(&buffer)?;
 
-    let mut out = serde_json::Map::new();
-    if let Some(metadata) = plan.metadata {
-        out.insert("__metadata__".into(), serde_json::to_value(metadata)?);
-    }
+    let mut metadata = plan.metadata.unwrap_or_default();
+    let mut header = serde_json::Map::new();
 
-    let mut write_plan = TensorPlaner::default();
+    // -- planning pass: resolve each tensor's treatment and compute its place in the
+    // output file from its shape and treatment alone, so the header (which the safetensors
+    // format requires up front) can be finalized and written before any tensor is actually
+    // quantized. The affine treatments are the one exception: their scale and zero_point are
+    // part of the header's `__metadata__`, so resolving them takes one pass over that
+    // tensor's values here; nothing is retained afterwards.
+    let mut planned = vec![];
+    let mut offset_planner = OffsetPlanner::default();
+    let mut max_in_elems = 0usize;
 
     for (name, tensor) in tensors.tensors() {
         let dtype = tensor.dtype();
-        let shape = tensor.shape();
-        let data = tensor.data();
-        let alignment_and_unit_size = dtype.size();
+        let shape = tensor.shape().to_vec();
+        let unit_size = dtype.size();
+        let n_elem = tensor.data_len() / unit_size;
 
         let k = QuantizeKey {
             dtype: util::dtype_str(&dtype).to_owned(),
-            shape: shape.to_vec(),
-        };
-        let Some(treatment) = plan.catalog.get(&k) else {
-            anyhow::bail!("treatment of tensor type not described in plan: type= {k:?}");
+            shape: shape.clone(),
         };
+        let treatment: QuantizeTreatment = plan
+            .overrides
+            .iter()
+            .find(|(pattern, _)| glob_match(pattern, &name))
+            .map(|(_, treatment)| *treatment)
+            .or_else(|| plan.catalog.get(&k).map(|info| info.treatment))
+            .with_context(|| {
+                format!("treatment of tensor not described in plan: name={name:?} type={k:?}")
+            })?;
 
-        let (dtype, alignment, data) = match treatment.treatment {
-            QuantizeTreatment::keep => (
-                util::dtype_str(&dtype),
-                alignment_and_unit_size,
-                data.into(),
-            ),
-            _ => {
+        let mut affine_params = None;
+        let (out_dtype, alignment, out_size) = match treatment {
+            QuantizeTreatment::keep => (util::dtype_str(&dtype), unit_size, tensor.data_len()),
+            QuantizeTreatment::i8_affine => {
                 ensure!(dtype == Dtype::F32, "only F32 tensors can be quantized");
-                let mut _loss = [0i64; 1 << 4];
-
-                let n_elem = data.len() / alignment_and_unit_size;
-                let mut work_buffer = vec![0u8; n_elem * 4];
-
-                ensure!(data.len() % alignment_and_unit_size == 0);
-                let ne_0 = *shape
-                    .iter()
-                    .find(|&&x| x != 1)
-                    .with_context(|| "tensor has no dim that != 1")?;
-
-                macro_rules! quantize {
-                    ($func : ident) => {{
-                        let cur_size = unsafe {
-                            ggml_sys::$func(
-                                std::mem::transmute(data.as_ptr()),
-                                std::mem::transmute(work_buffer.as_mut_ptr()),
-                                n_elem.try_into()?,
-                                ne_0.try_into()?,
-                                _loss.as_mut_ptr(),
-                            )
-                        };
-                        work_buffer[0..cur_size].to_vec()
-                    }};
-                }
-
-                match treatment.treatment {
-                    QuantizeTreatment::keep => unreachable!(),
-
-                    // == float32 delta/min  ==
-                    //
-                    QuantizeTreatment::q4_0 => {
-                        let converted = quantize!(ggml_quantize_q4_0);
-                        ("q4_0", 4, converted.into())
-                    }
-                    QuantizeTreatment::q4_1 => {
-                        let converted = quantize!(ggml_quantize_q4_1);
-                        ("q4_1", 4, converted.into())
-                    }
-                    QuantizeTreatment::q8_0 => {
-                        let converted = quantize!(ggml_quantize_q8_0);
-                        ("q8_0", 4, converted.into())
-                    }
-
-                    // == float16 delta/min ==
-                    //
-                    // QuantizeTreatment::q4_2 => {
-                    //     let converted = quantize!(ggml_quantize_q4_2);
-                    //     ("q4_2", 2, converted.into())
-                    // }
-                    QuantizeTreatment::q5_0 => {
-                        let converted = quantize!(ggml_quantize_q5_0);
-                        ("q5_0", 2, converted.into())
-                    }
-                    QuantizeTreatment::q5_1 => {
-                        let converted = quantize!(ggml_quantize_q5_1);
-                        ("q5_1", 2, converted.into())
-                    }
-                }
+                let data_f32: &[f32] =
+                    unsafe { std::slice::from_raw_parts(tensor.data().as_ptr().cast(), n_elem) };
+                let scale = i8_affine_scale(data_f32);
+                affine_params = Some((scale, 0));
+                metadata.insert(format!("{name}.scale"), scale.to_string());
+                metadata.insert(format!("{name}.zero_point"), 0.to_string());
+                (util::dtype_str(&Dtype::I8), 1, n_elem)
+            }
+            QuantizeTreatment::u8_affine => {
+                ensure!(dtype == Dtype::F32, "only F32 tensors can be quantized");
+                let data_f32: &[f32] =
+                    unsafe { std::slice::from_raw_parts(tensor.data().as_ptr().cast(), n_elem) };
+                let (scale, zero_point) = u8_affine_params(data_f32);
+                affine_params = Some((scale, zero_point));
+                metadata.insert(format!("{name}.scale"), scale.to_string());
+                metadata.insert(format!("{name}.zero_point"), zero_point.to_string());
+                (util::dtype_str(&Dtype::U8), 1, n_elem)
+            }
+            QuantizeTreatment::q4_0
+            | QuantizeTreatment::q4_1
+            | QuantizeTreatment::q5_0
+            | QuantizeTreatment::q5_1
+            | QuantizeTreatment::q8_0 => {
+                // only the ggml block formats actually use the scratch buffer (the affine
+                // treatments write straight into their own `Vec`), so only they should grow it
+                max_in_elems = max_in_elems.max(n_elem);
+                let (block_elems, block_bytes) = ggml_block_layout(treatment);
+                let n_blocks = next_multiple_of(n_elem, block_elems) / block_elems;
+                (ggml_treatment_name(treatment), 4, n_blocks * block_bytes)
             }
         };
-        let offsets = write_plan.plan_write(alignment, data);
-        out.insert(
+
+        let (_, start, end) = offset_planner.plan(alignment, out_size);
+        header.insert(
+            name.clone(),
+            json!({ "dtype": out_dtype, "shape": shape, "data_offsets": (start, end) }),
+        );
+        planned.push(PlannedTensor {
             name,
-            json!({ "dtype": dtype, "shape": shape, "offsets": offsets }),
+            treatment,
+            shape,
+            affine_params,
+            offsets: (start, end),
+        });
+    }
+
+    if !metadata.is_empty() {
+        header.insert("__metadata__".into(), serde_json::to_value(&metadata)?);
+    }
+
+    let out_file = File::create(&args.model_out)?;
+    let header_bytes = serde_json::to_vec(&header)?;
+    let header_len = next_multiple_of(header_bytes.len(), 8);
+    let padding = header_len - header_bytes.len();
+    (&out_file).write_all(&(header_len as u64).to_le_bytes())?;
+    (&out_file).write_all(&header_bytes)?;
+    (&out_file).write_all(&vec![0u8; padding])?;
+
+    // each tensor's place in the file is already fixed by the planning pass, so pre-size the
+    // file and have each worker `pwrite` its bytes straight to its own offset -- no reorder
+    // buffer is needed, and each worker holds at most one tensor's output in memory at a time
+    let data_region_start = 8 + header_len;
+    out_file.set_len((data_region_start + offset_planner.free_offset) as u64)?;
+
+    // only the ggml block formats use `scratch` (see the comment where `max_in_elems` is
+    // grown above), so size it for those alone instead of the model's largest tensor overall
+    let scratch_size = max_in_elems * 4;
+
+    let mut model_loss = [0i64; 1 << 4];
+    let results: Vec<anyhow::Result<Option<[i64; 16]>>> = planned
+        .par_iter()
+        .map_init(
+            move || vec![0u8; scratch_size],
+            |scratch, plan| -> anyhow::Result<Option<[i64; 16]>> {
+                let tensor = tensors
+                    .tensor(&plan.name)
+                    .with_context(|| format!("tensor {:?} vanished mid-quantize", plan.name))?;
+                let (bytes, loss) = quantize_tensor(plan, tensor.dtype(), &tensor.data(), scratch)?;
+                out_file.write_at(&bytes, (data_region_start + plan.offsets.0) as u64)?;
+                Ok(loss)
+            },
+        )
+        .collect();
+
+    for (plan, result) in planned.iter().zip(results) {
+        if let Some(loss) = result? {
+            println!("{}: loss histogram = {loss:?}", plan.name);
+            for (bucket, count) in model_loss.iter_mut().zip(loss) {
+                *bucket += count;
+            }
+        }
+    }
+
+    println!("model: loss histogram = {model_loss:?}");
+
+    Ok(())
+}
+
+/// How closely a dequantized tensor reproduces its original F32 values.
+///
+/// Borrowed from tract's `Approximation` model: a pair of thresholds
+/// (`atol`, `rtol`) such that a tensor passes a tier if
+/// `|a-b| <= atol + rtol*|b|` holds for every element.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Approximation {
+    Exact,
+    Close,
+    Approximate,
+    Fails,
+}
+
+impl std::fmt::Display for Approximation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+/// atol/rtol for the `Close` and `Approximate` tiers, for a given quantization treatment.
+///
+/// `q4_0`/`q4_1`/`q8_0` keep an F32-scale delta/min, `q5_0`/`q5_1` an F16-scale one (see the
+/// block sizes in [`ggml_block_layout`]), so they get correspondingly tighter/looser tolerances.
+fn tolerances(treatment: &str) -> ((f64, f64), (f64, f64)) {
+    match treatment {
+        "q5_0" | "q5_1" => ((1e-3, 1e-3), (1e-2, 5e-2)),
+        _ => ((1e-7, 1e-7), (1e-4, 5e-4)),
+    }
+}
+
+fn classify(dequantized: &[f32], original: &[f32], treatment: &str) -> (f64, f64, Approximation) {
+    let ((close_atol, close_rtol), (approx_atol, approx_rtol)) = tolerances(treatment);
+
+    let mut max_abs_err = 0f64;
+    let mut max_rel_err = 0f64;
+    let mut is_exact = true;
+    let mut is_close = true;
+    let mut is_approx = true;
+
+    for (&a, &b) in dequantized.iter().zip(original) {
+        let (a, b) = (a as f64, b as f64);
+        let abs_err = (a - b).abs();
+        max_abs_err = max_abs_err.max(abs_err);
+        if b != 0.0 {
+            max_rel_err = max_rel_err.max(abs_err / b.abs());
+        }
+
+        is_exact &= abs_err == 0.0;
+        is_close &= abs_err <= close_atol + close_rtol * b.abs();
+        is_approx &= abs_err <= approx_atol + approx_rtol * b.abs();
+    }
+
+    let approximation = if is_exact {
+        Approximation::Exact
+    } else if is_close {
+        Approximation::Close
+    } else if is_approx {
+        Approximation::Approximate
+    } else {
+        Approximation::Fails
+    };
+
+    (max_abs_err, max_rel_err, approximation)
+}
+
+/// Dequantize `data` (as produced by [`do_quantize`] for `treatment`) back to F32.
+fn dequantize(treatment: &str, data: &[u8], n_elem: usize, ne_0: usize) -> anyhow::Result<Vec<f32>> {
+    let mut out = vec![0f32; n_elem];
+
+    macro_rules! dequantize {
+        ($func:ident) => {
+            unsafe {
+                ggml_sys::$func(
+                    std::mem::transmute(data.as_ptr()),
+                    out.as_mut_ptr(),
+                    n_elem.try_into()?,
+                    ne_0.try_into()?,
+                )
+            }
+        };
+    }
+
+    match treatment {
+        "q4_0" => dequantize!(ggml_dequantize_q4_0),
+        "q4_1" => dequantize!(ggml_dequantize_q4_1),
+        "q5_0" => dequantize!(ggml_dequantize_q5_0),
+        "q5_1" => dequantize!(ggml_dequantize_q5_1),
+        "q8_0" => dequantize!(ggml_dequantize_q8_0),
+        other => bail!("no dequantizer for treatment {other:?}"),
+    }
+
+    Ok(out)
+}
+
+fn do_verify(args: VerifyArgs) -> anyhow::Result<()> {
+    let orig_file = File::open(&args.model_orig)?;
+    let orig_buffer = unsafe { MmapOptions::new().map(&orig_file)? };
+    let orig_tensors = SafeTensors::deserialize(&orig_buffer)?;
+
+    let quantized_file = File::open(&args.model_quantized)?;
+    let mut quantized_reader = std::io::BufReader::new(&quantized_file);
+    let quantized_header = util::read_header(&mut quantized_reader)?;
+    let quantized_buffer = unsafe { MmapOptions::new().map(&quantized_file)? };
+    let quantized_tensors = util::load_tensors(
+        &quantized_header,
+        &quantized_buffer,
+        util::WhereTheSliceStarts::StartOfFile,
+    )?;
+    let metadata: HashMap<String, String> = quantized_header
+        .data
+        .get("__metadata__")
+        .and_then(|v| serde_json::from_value(v.clone()).ok())
+        .unwrap_or_default();
+
+    let mut worst = Approximation::Exact;
+    for (name, tensor) in &quantized_tensors {
+        let treatment = tensor.dtype.as_str();
+        // an `i8_affine`/`u8_affine` tensor records its scale/zero_point in `__metadata__`;
+        // a plain `keep` tensor of the same dtype (e.g. a U8 mask) has none
+        let affine_params = match treatment {
+            "I8" | "U8" => metadata
+                .get(&format!("{name}.scale"))
+                .zip(metadata.get(&format!("{name}.zero_point"))),
+            _ => None,
+        };
+        // plain dtypes (F32, F16, ...) and plain `keep` I8/U8 tensors have nothing to verify
+        if !matches!(treatment, "q4_0" | "q4_1" | "q5_0" | "q5_1" | "q8_0") && affine_params.is_none()
+        {
+            continue;
+        }
+
+        let orig = orig_tensors
+            .tensor(name)
+            .with_context(|| format!("quantized tensor {name:?} missing from original model"))?;
+        ensure!(
+            orig.dtype() == Dtype::F32,
+            "original tensor {name:?} is not F32"
+        );
+        let orig_bytes = orig.data();
+        let orig_data: &[f32] = unsafe {
+            std::slice::from_raw_parts(orig_bytes.as_ptr().cast(), orig_bytes.len() / 4)
+        };
+
+        let dequantized = if let Some((scale, zero_point)) = affine_params {
+            let scale: f32 = scale
+                .parse()
+                .with_context(|| format!("tensor {name:?} has a non-numeric scale"))?;
+            let zero_point: i32 = zero_point
+                .parse()
+                .with_context(|| format!("tensor {name:?} has a non-numeric zero_point"))?;
+            dequantize_affine(treatment, tensor.data, scale, zero_point)?
+        } else {
+            let ne_0 = *tensor
+                .shape
+                .iter()
+                .find(|&&x| x != 1)
+                .with_context(|| format!("tensor {name:?} has no dim that != 1"))?;
+            dequantize(treatment, tensor.data, orig_data.len(), ne_0)?
+        };
+
+        let (max_abs_err, max_rel_err, approximation) =
+            classify(&dequantized, orig_data, treatment);
+        worst = worst.max(approximation);
+        println!(
+            "{name}: treatment={treatment} max_abs_err={max_abs_err:e} max_rel_err={max_rel_err:e} -> {approximation}"
         );
     }
 
-    let mut writer = File::create(args.model_out)?;
-    let header = serde_json::to_vec(&out)?;
-    let header_len = next_multiple_of(header.len(), 8);
-    let padding = header_len - header.len();
-    writer.write_all(&(header_len as u64).to_le_bytes())?;
-    writer.write_all(&header)?;
-    writer.write_all(&vec![0u8; padding])?;
+    println!("model: worst tensor approximation = {worst}");
 
-    for a in write_plan.tensors {
-        writer.write_all(&vec![0u8; a.leading_padding])?;
-        writer.write_all(&a.data)?;
+    Ok(())
+}
+
+/// Recover F32 values from an `i8_affine`/`u8_affine`-quantized tensor:
+/// `x = (q - zero_point) * scale` (the inverse of [`do_quantize`]'s affine branches).
+fn dequantize_affine(treatment: &str, data: &[u8], scale: f32, zero_point: i32) -> anyhow::Result<Vec<f32>> {
+    match treatment {
+        "I8" => Ok(data
+            .iter()
+            .map(|&b| (b as i8 as f32) * scale)
+            .collect()),
+        "U8" => Ok(data
+            .iter()
+            .map(|&b| (b as i32 - zero_point) as f32 * scale)
+            .collect()),
+        other => bail!("{other:?} is not an affine-quantized dtype"),
     }
+}
 
+/// Reconstruct a plain F32 `.safetensors` file from any file produced by [`do_quantize`]:
+/// ggml-block-quantized tensors are dequantized via `ggml_dequantize_*`, affine-quantized
+/// ones via their `scale`/`zero_point` in `__metadata__`, and `keep` tensors are passed
+/// through unchanged (they were never quantized, so there's nothing to recover).
+///
+/// Reads the header with [`util::read_header`]/[`util::load_tensors`] rather than
+/// `SafeTensors::deserialize`, since a quantized file's non-standard dtype strings
+/// (`"q4_0"`, ...) don't round-trip through the official crate's strict `Dtype` enum.
+fn do_dequantize(args: DequantizeArgs) -> anyhow::Result<()> {
+    let model_in = File::open(&args.model_in)?;
+    let mut reader = std::io::BufReader::new(&model_in);
+    let header = util::read_header(&mut reader)?;
+    let buffer = unsafe { MmapOptions::new().map(&model_in)? };
+    let tensors = util::load_tensors(&header, &buffer, util::WhereTheSliceStarts::StartOfFile)?;
+
+    let metadata: HashMap<String, String> = header
+        .data
+        .get("__metadata__")
+        .and_then(|v| serde_json::from_value(v.clone()).ok())
+        .unwrap_or_default();
+
+    struct Decoded {
+        dtype: Dtype,
+        shape: Vec<usize>,
+        data: Vec<u8>,
+    }
+    let mut decoded: HashMap<String, Decoded> = HashMap::new();
+
+    for (name, tensor) in &tensors {
+        let treatment = tensor.dtype.as_str();
+
+        if matches!(treatment, "q4_0" | "q4_1" | "q5_0" | "q5_1" | "q8_0") {
+            let n_elem: usize = tensor.shape.iter().product();
+            let ne_0 = *tensor
+                .shape
+                .iter()
+                .find(|&&x| x != 1)
+                .with_context(|| format!("tensor {name:?} has no dim that != 1"))?;
+            let values = dequantize(treatment, tensor.data, n_elem, ne_0)?;
+            decoded.insert(
+                name.clone(),
+                Decoded {
+                    dtype: Dtype::F32,
+                    shape: tensor.shape.clone(),
+                    data: f32_to_bytes(values),
+                },
+            );
+            continue;
+        }
+
+        if let (Some(scale), Some(zero_point)) = (
+            metadata.get(&format!("{name}.scale")),
+            metadata.get(&format!("{name}.zero_point")),
+        ) {
+            let scale: f32 = scale
+                .parse()
+                .with_context(|| format!("tensor {name:?} has a non-numeric scale"))?;
+            let zero_point: i32 = zero_point
+                .parse()
+                .with_context(|| format!("tensor {name:?} has a non-numeric zero_point"))?;
+            let values = dequantize_affine(treatment, tensor.data, scale, zero_point)?;
+            decoded.insert(
+                name.clone(),
+                Decoded {
+                    dtype: Dtype::F32,
+                    shape: tensor.shape.clone(),
+                    data: f32_to_bytes(values),
+                },
+            );
+            continue;
+        }
+
+        // a `keep` tensor: never quantized, so it passes through unchanged
+        decoded.insert(
+            name.clone(),
+            Decoded {
+                dtype: util::dtype_from_str(treatment)?,
+                shape: tensor.shape.clone(),
+                data: tensor.data.to_vec(),
+            },
+        );
+    }
+
+    let tensor_slices: Vec<(String, TensorView)> = decoded
+        .iter()
+        .map(|(name, t)| {
+            (
+                name.clone(),
+                TensorView {
+                    dtype: t.dtype,
+                    shape: t.shape.clone(),
+                    data: &t.data,
+                },
+            )
+        })
+        .collect();
+
+    safetensors::serialize_to_file(tensor_slices, &None, &args.model_out)?;
     Ok(())
 }
 
+fn f32_to_bytes(values: Vec<f32>) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(values.len() * 4);
+    for v in values {
+        bytes.extend_from_slice(&v.to_le_bytes());
+    }
+    bytes
+}
+
+/// Computes byte offsets/padding from a sequence of (alignment, size) requests alone,
+/// without ever touching the tensor data itself.
 #[derive(Default)]
-struct TensorPlaner<'a> {
+struct OffsetPlanner {
     /// first free byte
     free_offset: usize,
-    tensors: Vec<TensorPlanerTensor<'a>>,
 }
 
-struct TensorPlanerTensor<'a> {
-    leading_padding: usize,
-    data: Cow<'a, [u8]>,
-}
-
-impl<'a> TensorPlaner<'a> {
-    fn plan_write(&mut self, alignment: usize, data: Cow<'a, [u8]>) -> (usize, usize) {
+impl OffsetPlanner {
+    /// Returns (leading padding before this tensor's data, start offset, end offset).
+    fn plan(&mut self, alignment: usize, size: usize) -> (usize, usize, usize) {
         let start = next_multiple_of(self.free_offset, alignment);
         let padding = start - self.free_offset;
-        let end = start + data.len();
-        self.tensors.push(TensorPlanerTensor {
-            leading_padding: padding,
-            data,
-        });
+        let end = start + size;
         self.free_offset = end;
-        (start, end)
+        (padding, start, end)
     }
 }
 
@@ -353,3 +1030,62 @@ fn next_multiple_of(lhs: usize, multiple: usize) -> usize {
         r => lhs + (multiple - r),
     }
 }
+
+#[test]
+fn test_round_ties_to_even() {
+    assert_eq!(round_ties_to_even(0.5), 0.0);
+    assert_eq!(round_ties_to_even(1.5), 2.0);
+    assert_eq!(round_ties_to_even(2.5), 2.0);
+    assert_eq!(round_ties_to_even(-0.5), 0.0);
+    assert_eq!(round_ties_to_even(-1.5), -2.0);
+    assert_eq!(round_ties_to_even(0.4), 0.0);
+    assert_eq!(round_ties_to_even(0.6), 1.0);
+}
+
+#[test]
+fn test_i8_affine_quantize_dequantize_round_trip() {
+    let values = [-1.0f32, -0.5, 0.0, 0.25, 0.75, 1.0];
+    let scale = i8_affine_scale(&values);
+
+    let quantized: Vec<u8> = values
+        .iter()
+        .map(|&x| round_ties_to_even(x / scale).clamp(-127.0, 127.0) as i8 as u8)
+        .collect();
+    let dequantized = dequantize_affine("I8", &quantized, scale, 0).unwrap();
+
+    for (&original, &recovered) in values.iter().zip(&dequantized) {
+        assert!(
+            (original - recovered).abs() <= scale,
+            "original={original} recovered={recovered} scale={scale}"
+        );
+    }
+}
+
+#[test]
+fn test_u8_affine_quantize_dequantize_round_trip() {
+    let values = [-1.0f32, -0.5, 0.0, 0.25, 0.75, 1.0];
+    let (scale, zero_point) = u8_affine_params(&values);
+
+    let quantized: Vec<u8> = values
+        .iter()
+        .map(|&x| (round_ties_to_even(x / scale) + zero_point as f32).clamp(0.0, 255.0) as u8)
+        .collect();
+    let dequantized = dequantize_affine("U8", &quantized, scale, zero_point).unwrap();
+
+    for (&original, &recovered) in values.iter().zip(&dequantized) {
+        assert!(
+            (original - recovered).abs() <= scale,
+            "original={original} recovered={recovered} scale={scale}"
+        );
+    }
+}
+
+#[test]
+fn test_u8_affine_params_constant_tensor() {
+    // a constant tensor has max == min, which would otherwise divide by zero;
+    // zero_point = round(-min/scale) = -2 is kept as is, so (q - zero_point) * scale
+    // recovers 2.0 from q = 0
+    let (scale, zero_point) = u8_affine_params(&[2.0, 2.0, 2.0]);
+    assert_eq!(scale, 1.0);
+    assert_eq!(zero_point, -2);
+}
@@ -0,0 +1,211 @@
+//! Minimal reader/writer for the NumPy `.npy` array format, as used inside
+//! `.npz` archives (a plain ZIP of `.npy` members, one per array) by dfdx's
+//! `nn::npz` module and numpy's own `savez`/`load`.
+
+use anyhow::{bail, ensure, Context};
+use safetensors::Dtype;
+
+pub struct NpyHeader {
+    pub descr: String,
+    pub fortran_order: bool,
+    pub shape: Vec<usize>,
+}
+
+/// Parse a `.npy` file's magic, version and header dict, returning the header
+/// and the remaining bytes (the raw array data).
+pub fn parse_npy(data: &[u8]) -> anyhow::Result<(NpyHeader, &[u8])> {
+    ensure!(data.len() >= 10, "npy file too short to contain a header");
+    ensure!(&data[0..6] == b"\x93NUMPY", "not a npy file (bad magic)");
+    let major = data[6];
+
+    let (header_len, header_start) = if major >= 2 {
+        (
+            u32::from_le_bytes(data[8..12].try_into()?) as usize,
+            12,
+        )
+    } else {
+        (u16::from_le_bytes(data[8..10].try_into()?) as usize, 10)
+    };
+    let data_start = header_start + header_len;
+    let header_str = std::str::from_utf8(
+        data.get(header_start..data_start)
+            .with_context(|| "npy header length runs past end of file")?,
+    )?;
+
+    Ok((
+        NpyHeader {
+            descr: extract_str_field(header_str, "descr")?,
+            fortran_order: extract_bool_field(header_str, "fortran_order")?,
+            shape: extract_shape_field(header_str, "shape")?,
+        },
+        &data[data_start..],
+    ))
+}
+
+/// Build a `.npy` file (magic + version + header dict, padded to a 64-byte
+/// boundary as numpy does) for a C-contiguous array of the given dtype and shape.
+/// Caller appends the raw little-endian array bytes after this header.
+pub fn build_npy_header(descr: &str, shape: &[usize]) -> Vec<u8> {
+    let shape_str = match shape {
+        [] => "()".to_owned(),
+        [d] => format!("({d},)"),
+        _ => format!(
+            "({})",
+            shape
+                .iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut dict = format!("{{'descr': '{descr}', 'fortran_order': False, 'shape': {shape_str}, }}");
+
+    const PREFIX_LEN: usize = 6 + 2 + 2; // magic + version + (v1.0) header-length field
+    let unpadded_len = PREFIX_LEN + dict.len() + 1; // +1 for the trailing '\n'
+    let padding = (64 - unpadded_len % 64) % 64;
+    dict.extend(std::iter::repeat(' ').take(padding));
+    dict.push('\n');
+
+    let header_len: u16 = dict.len().try_into().expect("npy header too large");
+    let mut out = Vec::with_capacity(PREFIX_LEN + dict.len());
+    out.extend_from_slice(b"\x93NUMPY");
+    out.push(1); // major version
+    out.push(0); // minor version
+    out.extend_from_slice(&header_len.to_le_bytes());
+    out.extend_from_slice(dict.as_bytes());
+    out
+}
+
+pub fn npy_dtype_to_safetensors(descr: &str) -> anyhow::Result<Dtype> {
+    Ok(match descr {
+        "<f8" | "=f8" => Dtype::F64,
+        "<f4" | "=f4" => Dtype::F32,
+        "<f2" | "=f2" => Dtype::F16,
+        "<i8" | "=i8" => Dtype::I64,
+        "<i4" | "=i4" => Dtype::I32,
+        "<i2" | "=i2" => Dtype::I16,
+        "|i1" => Dtype::I8,
+        "<u8" | "=u8" => Dtype::U64,
+        "<u4" | "=u4" => Dtype::U32,
+        "<u2" | "=u2" => Dtype::U16,
+        "|u1" => Dtype::U8,
+        "|b1" => Dtype::BOOL,
+        other => bail!("unsupported numpy dtype: {other:?} (only little-endian/byte dtypes are supported)"),
+    })
+}
+
+pub fn safetensors_dtype_to_npy(dtype: Dtype) -> anyhow::Result<&'static str> {
+    Ok(match dtype {
+        Dtype::F64 => "<f8",
+        Dtype::F32 => "<f4",
+        Dtype::F16 => "<f2",
+        Dtype::I64 => "<i8",
+        Dtype::I32 => "<i4",
+        Dtype::I16 => "<i2",
+        Dtype::I8 => "|i1",
+        Dtype::U64 => "<u8",
+        Dtype::U32 => "<u4",
+        Dtype::U16 => "<u2",
+        Dtype::U8 => "|u1",
+        Dtype::BOOL => "|b1",
+        other => bail!("numpy has no native equivalent of dtype {other:?} (e.g. bfloat16)"),
+    })
+}
+
+/// Pull `'field': value,` out of a npy header dict without a full python literal parser.
+fn field_value(header: &str, field: &str) -> anyhow::Result<String> {
+    let needle = format!("'{field}':");
+    let after = header
+        .split_once(&needle)
+        .with_context(|| format!("npy header missing {field:?} field: {header:?}"))?
+        .1
+        .trim_start();
+    let end = after
+        .find(',')
+        .with_context(|| format!("npy header {field:?} field has no trailing comma: {header:?}"))?;
+    Ok(after[..end].trim().to_owned())
+}
+
+fn extract_str_field(header: &str, field: &str) -> anyhow::Result<String> {
+    let raw = field_value(header, field)?;
+    Ok(raw.trim_matches(['\'', '"']).to_owned())
+}
+
+fn extract_bool_field(header: &str, field: &str) -> anyhow::Result<bool> {
+    match field_value(header, field)?.as_str() {
+        "True" => Ok(true),
+        "False" => Ok(false),
+        other => bail!("npy header field {field:?} is not a python bool: {other:?}"),
+    }
+}
+
+fn extract_shape_field(header: &str, field: &str) -> anyhow::Result<Vec<usize>> {
+    let needle = format!("'{field}':");
+    let after = header
+        .split_once(&needle)
+        .with_context(|| format!("npy header missing {field:?} field: {header:?}"))?
+        .1
+        .trim_start();
+    ensure!(after.starts_with('('), "npy header {field:?} is not a tuple: {after:?}");
+    let end = after
+        .find(')')
+        .with_context(|| format!("npy header {field:?} tuple is unterminated: {after:?}"))?;
+    after[1..end]
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<usize>().with_context(|| format!("bad shape element {s:?}")))
+        .collect()
+}
+
+#[test]
+fn test_npy_header_round_trip() {
+    let header = build_npy_header("<f4", &[2, 3]);
+    let (parsed, rest) = parse_npy(&header).unwrap();
+    assert_eq!(parsed.descr, "<f4");
+    assert_eq!(parsed.fortran_order, false);
+    assert_eq!(parsed.shape, vec![2, 3]);
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn test_npy_header_round_trip_scalar_shape() {
+    let header = build_npy_header("|u1", &[]);
+    let (parsed, _) = parse_npy(&header).unwrap();
+    assert_eq!(parsed.descr, "|u1");
+    assert_eq!(parsed.shape, Vec::<usize>::new());
+}
+
+#[test]
+fn test_npy_header_round_trip_single_dim_shape() {
+    let header = build_npy_header("<i8", &[5]);
+    let (parsed, _) = parse_npy(&header).unwrap();
+    assert_eq!(parsed.shape, vec![5]);
+}
+
+#[test]
+fn test_npy_header_is_padded_to_64_bytes() {
+    let header = build_npy_header("<f4", &[2, 3]);
+    assert_eq!(header.len() % 64, 0);
+}
+
+#[test]
+fn test_npy_dtype_round_trip() {
+    for dtype in [
+        Dtype::F64,
+        Dtype::F32,
+        Dtype::F16,
+        Dtype::I64,
+        Dtype::I32,
+        Dtype::I16,
+        Dtype::I8,
+        Dtype::U64,
+        Dtype::U32,
+        Dtype::U16,
+        Dtype::U8,
+        Dtype::BOOL,
+    ] {
+        let descr = safetensors_dtype_to_npy(dtype).unwrap();
+        assert_eq!(npy_dtype_to_safetensors(descr).unwrap(), dtype);
+    }
+}
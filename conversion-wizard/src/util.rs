@@ -1,3 +1,13 @@
+// The workspace's `safetensors/` crate (depended on here as `raw_safetensors`, since the
+// `safetensors` name is already taken by the crates.io crate providing `Dtype`/`SafeTensors`)
+// reads headers into a plain `serde_json::Value` and keeps each dtype as a string instead of a
+// fixed `Dtype`, so it tolerates the non-standard dtype strings (`"q4_0"`, ...) that this
+// tool's own quantized files use and the official crate's strict decoder would reject.
+pub(crate) use raw_safetensors::{
+    load_tensors, read_header, LoadTensorError, SafetensorsHeader, TensorInfo,
+    WhereTheSliceStarts,
+};
+
 pub(crate) fn dtype_str(dtype: &safetensors::Dtype) -> &'static str {
     match dtype {
         safetensors::Dtype::BOOL => "BOOL",
@@ -16,3 +26,23 @@ pub(crate) fn dtype_str(dtype: &safetensors::Dtype) -> &'static str {
         what => unreachable!("Unknown dtype: {what:?}"),
     }
 }
+
+/// Inverse of [`dtype_str`], for plain (non-quantized) dtype strings stored in a header.
+pub(crate) fn dtype_from_str(dtype: &str) -> anyhow::Result<safetensors::Dtype> {
+    Ok(match dtype {
+        "BOOL" => safetensors::Dtype::BOOL,
+        "U8" => safetensors::Dtype::U8,
+        "I8" => safetensors::Dtype::I8,
+        "I16" => safetensors::Dtype::I16,
+        "U16" => safetensors::Dtype::U16,
+        "F16" => safetensors::Dtype::F16,
+        "BF16" => safetensors::Dtype::BF16,
+        "I32" => safetensors::Dtype::I32,
+        "U32" => safetensors::Dtype::U32,
+        "F32" => safetensors::Dtype::F32,
+        "F64" => safetensors::Dtype::F64,
+        "I64" => safetensors::Dtype::I64,
+        "U64" => safetensors::Dtype::U64,
+        other => anyhow::bail!("unknown dtype: {other:?}"),
+    })
+}